@@ -5,8 +5,9 @@ extern crate welder;
 
 use std::io;
 
-use welder::{Error, ErrorExt, CommonErrorData, ErrorLocation, ConstructError,
-             FromError, print_error_stack};
+use welder::{Error, ErrorExt, ErrorKind, Severity, CommonErrorData, ErrorLocation, ConstructError,
+             FromError, Backtrace, WithContext, ResultExt, print_error_stack, print_error_stack_json};
+use welder::fs;
 
 
 #[deriving(Eq, PartialEq, Clone)]
@@ -38,6 +39,27 @@ impl Error for CliError {
         self.data.location.clone()
     }
 
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.data.backtrace.as_ref()
+    }
+
+    fn kind(&self) -> ErrorKind {
+        match self.data.kind {
+            CliErrorKind::NotFound => ErrorKind::NotFound,
+            CliErrorKind::NoPermission => ErrorKind::PermissionDenied,
+            CliErrorKind::InternalIoError(ref err) => (err as &Error).kind(),
+        }
+    }
+
+    fn severity(&self) -> Severity {
+        match self.data.kind {
+            // Missing items can be worked around by a caller; a denied
+            // permission can't, so it's not worth retrying.
+            CliErrorKind::NoPermission => Severity::Fatal,
+            _ => Severity::Recoverable,
+        }
+    }
+
     fn cause(&self) -> Option<&Error> {
         match self.data.kind {
             CliErrorKind::InternalIoError(ref err) => Some(err as &Error),
@@ -55,6 +77,7 @@ impl ConstructError<(CliErrorKind, &'static str)> for CliError {
                 kind: kind,
                 detail: None,
                 location: loc,
+                backtrace: Backtrace::capture(),
             }
         }
     }
@@ -68,6 +91,7 @@ impl FromError<io::IoError> for CliError {
                 kind: CliErrorKind::InternalIoError(err),
                 detail: None,
                 location: loc,
+                backtrace: Backtrace::capture(),
             }
         }
     }
@@ -93,6 +117,56 @@ fn an_io_error() -> Result<(), CliError> {
     Ok(())
 }
 
+fn an_fs_error() -> Result<(), fs::FsError> {
+    try!(fs::open(&Path::new("/missing.txt")));
+    Ok(())
+}
+
+fn an_fs_roundtrip() -> Result<String, fs::FsError> {
+    let path = Path::new("/tmp/welder_test.txt");
+    {
+        let mut file = try!(fs::create(&path));
+        let _ = file.write_line("hello from welder");
+    }
+    let file = try!(fs::open(&path));
+    let mut reader = io::BufferedReader::new(file);
+    fs::read_line(&path, &mut reader)
+}
+
+fn read_config() -> Result<String, WithContext> {
+    read_first_line().context("reading config")
+}
+
+fn check_positive(n: int) -> Result<(), CliError> {
+    ensure!(n > 0, CliErrorKind::NotFound, "n must be positive");
+    Ok(())
+}
+
+fn always_fails() -> Result<(), CliError> {
+    bail!(CliErrorKind::NoPermission, "Access not possible");
+}
+
+fn recover_or_default() -> Result<int, CliError> {
+    // `NotFound` is recoverable, so `try_recover!` falls back instead of
+    // propagating the error.
+    let value = try_recover!(test_missing_item().map(|_| 1i), -1);
+    Ok(value)
+}
+
+fn recover_fatal() -> Result<int, CliError> {
+    // `NoPermission` is `Fatal`, so `try_recover!` re-propagates it
+    // instead of invoking the fallback.
+    let value = try_recover!(always_fails().map(|_| 1i), -1);
+    Ok(value)
+}
+
+fn recover_combinator() -> Result<int, CliError> {
+    // Same behavior as `recover_or_default`, expressed as a combinator
+    // over an existing `Result` instead of an inline macro.
+    let value = try!(test_missing_item().map(|_| 1i).or_recover(|_| -1));
+    Ok(value)
+}
+
 
 fn main() {
     match bar() {
@@ -101,6 +175,11 @@ fn main() {
                 Some(_) => { println!("Got a compatible cli error!"); }
                 None => { println!("This was not a cli error!"); }
             }
+            // `kind()` lets a caller branch on the classification of an
+            // error without downcasting to the concrete `CliError` type.
+            if (&e as &Error).kind() == ErrorKind::NotFound {
+                println!("kind() correctly reports NotFound");
+            }
             print_error_stack(&e);
         },
         Ok(_) => {},
@@ -110,4 +189,62 @@ fn main() {
         Err(e) => print_error_stack(&e),
         Ok(_) => {},
     }
+
+    // `detail()` on this error names both the operation and the path,
+    // unlike the bare `io::IoError` printed by `an_io_error` above.
+    match an_fs_error() {
+        Err(e) => print_error_stack(&e),
+        Ok(_) => {},
+    }
+
+    // Exercises `fs::create` and `fs::read_line` the same way `an_fs_error`
+    // exercises `fs::open`.
+    match an_fs_roundtrip() {
+        Ok(line) => println!("an_fs_roundtrip() = {}", line),
+        Err(e) => print_error_stack(&e),
+    }
+
+    // `.context()` wraps the underlying `io::IoError` without defining a
+    // whole new error type; `format_trace` still walks through to it.
+    match read_config() {
+        Err(e) => print_error_stack(&e),
+        Ok(_) => {},
+    }
+
+    // `ensure!` turns the precondition check into a one-liner.
+    match check_positive(-1) {
+        Err(e) => print_error_stack(&e),
+        Ok(_) => {},
+    }
+
+    // `bail!` reads more naturally than `fail!` for an unconditional abort.
+    match always_fails() {
+        Err(e) => print_error_stack(&e),
+        Ok(_) => {},
+    }
+
+    // `try_recover!` falls back to -1 because `NotFound` is recoverable.
+    match recover_or_default() {
+        Ok(value) => println!("recover_or_default() = {}", value),
+        Err(e) => print_error_stack(&e),
+    }
+
+    // `try_recover!` re-propagates because `NoPermission` is fatal.
+    match recover_fatal() {
+        Err(e) => print_error_stack(&e),
+        Ok(_) => {},
+    }
+
+    // `ResultExt::or_recover` is the combinator form of the same check.
+    match recover_combinator() {
+        Ok(value) => println!("recover_combinator() = {}", value),
+        Err(e) => print_error_stack(&e),
+    }
+
+    // Same cause chain as the very first `print_error_stack` call above,
+    // but serialized as JSON for a logging pipeline to consume.
+    match bar() {
+        Err(e) => print_error_stack_json(&e),
+        Ok(_) => {},
+    }
 }
@@ -0,0 +1,190 @@
+//! Lazy backtrace capture for welded errors.
+//!
+//! Walking the stack and collecting raw frame addresses is cheap, but
+//! resolving those addresses into symbol names (via `dladdr` on unix) is
+//! not.  `Backtrace` therefore captures the raw addresses eagerly (if
+//! enabled at all) and defers the expensive symbolization step until the
+//! backtrace is first formatted.
+
+use std::fmt;
+use std::mem;
+use std::os;
+use std::sync::{Mutex, Once, ONCE_INIT};
+
+static CAPTURE_ENABLED_INIT: Once = ONCE_INIT;
+static mut CAPTURE_ENABLED: bool = false;
+
+/// Checks (once) whether `RUST_BACKTRACE` requests backtrace capture.
+///
+/// The environment is only ever inspected the first time this is called;
+/// the result is cached so that later error construction pays nothing
+/// beyond a single atomic check.
+fn capture_enabled() -> bool {
+    unsafe {
+        CAPTURE_ENABLED_INIT.doit(|| {
+            CAPTURE_ENABLED = match os::getenv("RUST_BACKTRACE") {
+                Some(ref val) if val.as_slice() != "0" => true,
+                _ => false,
+            };
+        });
+        CAPTURE_ENABLED
+    }
+}
+
+enum State {
+    Unresolved(Vec<uint>),
+    Resolved(Vec<String>),
+}
+
+/// A lazily resolved stack trace captured at error construction time.
+///
+/// The frame addresses are collected when the error is created, but the
+/// (comparatively expensive) symbol names are only resolved the first
+/// time the backtrace is formatted.  The resolution cache is guarded by
+/// a lock so that `Backtrace` stays `Send`, as required by the `Error`
+/// trait.
+pub struct Backtrace {
+    state: Mutex<State>,
+}
+
+impl Backtrace {
+
+    /// Captures the current call stack if `RUST_BACKTRACE` is set to a
+    /// value other than `0`.
+    ///
+    /// Returns `None` when backtrace capture is disabled so that release
+    /// runs which never set the environment variable pay nothing for
+    /// constructing an error.
+    pub fn capture() -> Option<Backtrace> {
+        if !capture_enabled() {
+            return None;
+        }
+        Some(Backtrace {
+            state: Mutex::new(State::Unresolved(unsafe { collect_frames() })),
+        })
+    }
+
+    /// Returns the resolved, human readable frames of this backtrace.
+    pub fn resolved_frames(&self) -> Vec<String> {
+        let mut state = self.state.lock();
+        let resolved = match *state {
+            State::Resolved(ref frames) => return frames.clone(),
+            State::Unresolved(ref addrs) => resolve_frames(addrs.as_slice()),
+        };
+        *state = State::Resolved(resolved.clone());
+        resolved
+    }
+}
+
+impl Clone for Backtrace {
+    fn clone(&self) -> Backtrace {
+        let state = self.state.lock();
+        Backtrace {
+            state: Mutex::new(match *state {
+                State::Resolved(ref frames) => State::Resolved(frames.clone()),
+                State::Unresolved(ref addrs) => State::Unresolved(addrs.clone()),
+            }),
+        }
+    }
+}
+
+impl fmt::Show for Backtrace {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, frame) in self.resolved_frames().iter().enumerate() {
+            try!(writeln!(fmt, "  {:4}: {}", idx, frame));
+        }
+        Ok(())
+    }
+}
+
+/// Upper bound on how far the `rbp` chain may climb from where capture
+/// started, used to bail out of the walk instead of chasing an arbitrary
+/// pointer (see `collect_frames`).
+static MAX_STACK_SPAN: uint = 8 * 1024 * 1024;
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn collect_frames() -> Vec<uint> {
+    // Walks the `rbp` chain to gather raw return addresses.  This is the
+    // same frame pointer trick libstd's own `RUST_BACKTRACE` support
+    // relies on, but it only holds up when every frame above us was
+    // compiled with frame pointers preserved, which is not the default
+    // for optimized builds on most modern toolchains.  To avoid chasing
+    // a bogus pointer into unrelated memory (and segfaulting just from
+    // constructing an error), each step is required to land further up
+    // a downward-growing stack and within a sane span of where the walk
+    // started; anything else aborts the walk early instead of
+    // dereferencing it.
+    let mut frames = vec![];
+    let mut rbp: *const uint;
+    asm!("movq %rbp, $0" : "=r"(rbp) ::: "volatile");
+    let start = rbp as uint;
+    while !rbp.is_null() && frames.len() < 64 {
+        if (rbp as uint) % mem::size_of::<uint>() != 0 {
+            break;
+        }
+        let next_rbp = *rbp;
+        if next_rbp <= rbp as uint || next_rbp - start > MAX_STACK_SPAN {
+            break;
+        }
+        let ret_addr = *rbp.offset(1);
+        if ret_addr == 0 {
+            break;
+        }
+        frames.push(ret_addr);
+        rbp = next_rbp as *const uint;
+    }
+    frames
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+unsafe fn collect_frames() -> Vec<uint> {
+    vec![]
+}
+
+#[cfg(unix)]
+mod symbolize {
+    use std::c_str::CString;
+    use std::mem;
+
+    #[repr(C)]
+    struct DlInfo {
+        dli_fname: *const i8,
+        dli_fbase: *mut u8,
+        dli_sname: *const i8,
+        dli_saddr: *mut u8,
+    }
+
+    #[link(name = "dl")]
+    extern "C" {
+        fn dladdr(addr: *const u8, info: *mut DlInfo) -> i32;
+    }
+
+    /// Resolves a single raw return address to a symbol name via `dladdr`,
+    /// falling back to the bare address when the symbol can't be found.
+    pub fn resolve(addr: uint) -> String {
+        unsafe {
+            let mut info: DlInfo = mem::zeroed();
+            if dladdr(addr as *const u8, &mut info) != 0 && !info.dli_sname.is_null() {
+                let name = CString::new(info.dli_sname, false);
+                match name.as_str() {
+                    Some(s) => return format!("0x{:x} - {}", addr, s),
+                    None => {}
+                }
+            }
+        }
+        format!("0x{:x} - <unknown>", addr)
+    }
+}
+
+#[cfg(not(unix))]
+mod symbolize {
+    /// No `dladdr` equivalent is wired up for this platform yet, so frames
+    /// are reported by address only.
+    pub fn resolve(addr: uint) -> String {
+        format!("0x{:x} - <unknown>", addr)
+    }
+}
+
+fn resolve_frames(addrs: &[uint]) -> Vec<String> {
+    addrs.iter().map(|&addr| symbolize::resolve(addr)).collect()
+}
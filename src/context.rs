@@ -0,0 +1,129 @@
+//! Typeless error-context chaining.
+//!
+//! Defining a whole new error type (with its own `CommonErrorData` and
+//! `ConstructError` impl) is overkill when all you want is to attach a
+//! human readable message to an error as it propagates up.  `WithContext`
+//! and the `ResultExt` trait provide that without forcing a new nominal
+//! type per layer.
+
+use std::fmt;
+
+use super::{Error, ErrorLocation, Backtrace, Severity};
+
+/// An error produced by [`ResultExt::context`](trait.ResultExt.html).
+///
+/// Wraps an arbitrary `Error` with a message describing what was being
+/// attempted.  In debug builds the recorded `location()` is the line in
+/// this module where `context`/`with_context` builds the `WithContext`,
+/// not the line of the caller that invoked it.  The wrapped error becomes
+/// the `cause()` of the `WithContext`, so existing consumers like
+/// `ErrorFormatter::format_trace` walk straight through it.
+pub struct WithContext {
+    message: String,
+    location: Option<ErrorLocation>,
+    backtrace: Option<Backtrace>,
+    cause: Box<Error>,
+}
+
+impl Error for WithContext {
+
+    fn name(&self) -> &str {
+        "WithContext"
+    }
+
+    fn description(&self) -> &str {
+        self.message.as_slice()
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        Some(&*self.cause)
+    }
+
+    fn location(&self) -> Option<ErrorLocation> {
+        self.location.clone()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.backtrace.as_ref()
+    }
+}
+
+impl fmt::Show for WithContext {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.message.as_slice())
+    }
+}
+
+/// Converts a value into the message stored on a `WithContext` error.
+///
+/// Implemented for `&'static str` and `String` so that both a cheap
+/// literal and an already-built message can be passed to `.context()`.
+pub trait IntoMessage {
+    fn into_message(self) -> String;
+}
+
+impl IntoMessage for &'static str {
+    fn into_message(self) -> String {
+        self.to_string()
+    }
+}
+
+impl IntoMessage for String {
+    fn into_message(self) -> String {
+        self
+    }
+}
+
+/// Extension methods for attaching context to a `Result`'s error.
+pub trait ResultExt<T, E> {
+
+    /// Wraps the error of this result with a message, turning it into a
+    /// `WithContext` whose `cause()` is the original error.
+    fn context<M: IntoMessage>(self, msg: M) -> Result<T, WithContext>;
+
+    /// Like `context` but only builds the message when the result is an
+    /// error, useful when the message is not free to construct.
+    fn with_context<M: IntoMessage, F: FnOnce() -> M>(self, f: F) -> Result<T, WithContext>;
+
+    /// Falls back to a computed value when the error is `Recoverable`,
+    /// otherwise propagates the original error unchanged.
+    ///
+    /// This is the combinator form of `try_recover!`; use it when you
+    /// have a `Result` value in hand rather than an expression you want
+    /// to unwrap inline.
+    fn or_recover<F: FnOnce(&E) -> T>(self, f: F) -> Result<T, E>;
+}
+
+impl<T, E: Error> ResultExt<T, E> for Result<T, E> {
+
+    fn context<M: IntoMessage>(self, msg: M) -> Result<T, WithContext> {
+        self.map_err(|err| WithContext {
+            message: msg.into_message(),
+            location: debug_error_location!(),
+            backtrace: Backtrace::capture(),
+            cause: box err as Box<Error>,
+        })
+    }
+
+    fn with_context<M: IntoMessage, F: FnOnce() -> M>(self, f: F) -> Result<T, WithContext> {
+        self.map_err(|err| WithContext {
+            message: f().into_message(),
+            location: debug_error_location!(),
+            backtrace: Backtrace::capture(),
+            cause: box err as Box<Error>,
+        })
+    }
+
+    fn or_recover<F: FnOnce(&E) -> T>(self, f: F) -> Result<T, E> {
+        match self {
+            Ok(val) => Ok(val),
+            Err(err) => {
+                if err.severity() == Severity::Recoverable {
+                    Ok(f(&err))
+                } else {
+                    Err(err)
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Path-annotating filesystem helpers.
+//!
+//! `io::IoError` alone drops the filename that caused it, so
+//! `ErrorFormatter` output can print the source line of the `fail!` site
+//! but can't say *which* file failed.  These thin wrappers around
+//! `io::File` convert a bare `io::IoError` into a welded error whose
+//! `detail()` names both the operation and the offending path.
+
+use std::io;
+
+use super::{Error, ErrorLocation, ErrorKind, CommonErrorData, ConstructError, Backtrace};
+
+/// An I/O error annotated with the path and operation that caused it.
+#[deriving(Clone)]
+pub struct FsError {
+    data: Box<CommonErrorData<io::IoErrorKind>>,
+}
+
+impl Error for FsError {
+
+    fn name(&self) -> &str {
+        "FsError"
+    }
+
+    fn description(&self) -> &str {
+        self.data.description
+    }
+
+    fn detail(&self) -> Option<String> {
+        self.data.detail.clone()
+    }
+
+    fn location(&self) -> Option<ErrorLocation> {
+        self.data.location.clone()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.data.backtrace.as_ref()
+    }
+
+    fn kind(&self) -> ErrorKind {
+        match self.data.kind {
+            io::FileNotFound | io::PathDoesntExist => ErrorKind::NotFound,
+            io::PermissionDenied => ErrorKind::PermissionDenied,
+            io::PathAlreadyExists => ErrorKind::AlreadyExists,
+            io::InvalidInput => ErrorKind::InvalidInput,
+            io::TimedOut => ErrorKind::TimedOut,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl ConstructError<(io::IoError, &'static str, Path)> for FsError {
+    fn construct_error((err, op, path): (io::IoError, &'static str, Path),
+                        loc: Option<ErrorLocation>) -> FsError {
+        FsError {
+            data: box CommonErrorData {
+                kind: err.kind,
+                description: "an I/O error occurred",
+                detail: Some(format!("failed to {} {}", op, path.display())),
+                location: loc,
+                backtrace: Backtrace::capture(),
+            }
+        }
+    }
+}
+
+fn wrap<T>(op: &'static str, path: &Path, result: io::IoResult<T>) -> Result<T, FsError> {
+    match result {
+        Ok(value) => Ok(value),
+        Err(err) => fail!(err, op, path.clone()),
+    }
+}
+
+/// Opens a file in read-only mode, like `io::File::open`, but on failure
+/// returns an `FsError` whose `detail()` names the offending path.
+pub fn open(path: &Path) -> Result<io::File, FsError> {
+    wrap("open", path, io::File::open(path))
+}
+
+/// Opens or creates a file for writing, like `io::File::create`.
+pub fn create(path: &Path) -> Result<io::File, FsError> {
+    wrap("create", path, io::File::create(path))
+}
+
+/// Reads a single line from a buffered reader over a path-backed file,
+/// annotating any failure with the given path.
+pub fn read_line(path: &Path, reader: &mut io::BufferedReader<io::File>) -> Result<String, FsError> {
+    wrap("read from", path, reader.read_line())
+}
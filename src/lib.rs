@@ -10,11 +10,16 @@
 #![feature(macro_rules)]
 #![feature(associated_types)]
 #![feature(while_let)]
+#![feature(asm)]
 
 use std::{raw, mem, fmt};
 use std::intrinsics::TypeId;
 use std::io;
 
+pub use backtrace::Backtrace;
+
+mod backtrace;
+
 
 /// Holds information related to the location of an error.
 #[deriving(Eq, PartialEq, Clone)]
@@ -55,6 +60,40 @@ impl fmt::Show for ErrorLocation {
 }
 
 
+/// A portable classification of errors, modeled on `std::io::IoErrorKind`.
+///
+/// Unlike `ErrorExt::cast`, which only succeeds when the error is of a
+/// known concrete type, `Error::kind` lets callers branch on the nature
+/// of an error even when it came from another crate.
+#[deriving(Eq, PartialEq, Clone, Show)]
+pub enum ErrorKind {
+    /// The requested entity was not found.
+    NotFound,
+    /// The operation lacked the necessary privileges.
+    PermissionDenied,
+    /// The entity already exists.
+    AlreadyExists,
+    /// The operation was interrupted and may be retried.
+    Interrupted,
+    /// A parameter was incorrect.
+    InvalidInput,
+    /// The operation timed out.
+    TimedOut,
+    /// Any other error kind not covered above.
+    Other,
+}
+
+/// Distinguishes errors a caller can sensibly work around from ones it
+/// can't, borrowing the recoverable/unrecoverable split from winnow's
+/// error handling.
+#[deriving(Eq, PartialEq, Clone, Show)]
+pub enum Severity {
+    /// The caller may reasonably try an alternative or retry.
+    Recoverable,
+    /// The caller should not attempt to recover; the error must propagate.
+    Fatal,
+}
+
 /// Trait that represents errors.
 ///
 /// Example:
@@ -96,7 +135,27 @@ pub trait Error: 'static + Send {
     /// The location of this error if available.
     fn location(&self) -> Option<ErrorLocation> { None }
 
-    /// This apparently needs to be here instead of 
+    /// A portable classification of this error.
+    ///
+    /// This lets callers match on the kind of an error across crate
+    /// boundaries without downcasting through `ErrorExt::cast`.
+    fn kind(&self) -> ErrorKind { ErrorKind::Other }
+
+    /// Whether a caller may reasonably recover from this error.
+    ///
+    /// Defaults to `Recoverable`; error authors should override this to
+    /// `Fatal` for errors (such as a broken config file) that alternative
+    /// or retry logic should not try to paper over.
+    fn severity(&self) -> Severity { Severity::Recoverable }
+
+    /// The stack trace captured when this error was constructed, if any.
+    ///
+    /// This is `None` unless the concrete error stores a `Backtrace` (for
+    /// instance in its `CommonErrorData`) and `RUST_BACKTRACE` was set to
+    /// a value other than `0` at capture time.
+    fn backtrace(&self) -> Option<&Backtrace> { None }
+
+    /// This apparently needs to be here instead of
     #[doc(hidden)]
     fn get_error_type(&self) -> TypeId { TypeId::of::<Self>() }
 }
@@ -130,7 +189,7 @@ impl<'a> ErrorExt<'a> for &'a Error {
 
 // local hack until $crate lands
 mod welder {
-    pub use super::{ConstructError, ErrorLocation};
+    pub use super::{ConstructError, ErrorLocation, Error, Severity};
 }
 
 
@@ -145,6 +204,8 @@ pub struct CommonErrorData<K: Eq> {
     pub description: &'static str,
     pub detail: Option<String>,
     pub location: Option<ErrorLocation>,
+    /// A lazily captured stack trace, if backtrace capture is enabled.
+    pub backtrace: Option<Backtrace>,
 }
 
 
@@ -166,6 +227,7 @@ pub struct CommonErrorData<K: Eq> {
 ///                 kind: kind,
 ///                 detail: None,
 ///                 location: loc,
+///                 backtrace: Backtrace::capture(),
 ///             }
 ///         }
 ///     }
@@ -201,6 +263,7 @@ impl<S: Error, E: FromError<S>> ConstructError<(S,)> for E {
 ///                 kind: InternalIoError(err),
 ///                 detail: None,
 ///                 location: loc,
+///                 backtrace: Backtrace::capture(),
 ///             }
 ///         }
 ///     }
@@ -269,6 +332,53 @@ macro_rules! fail {
     });
 }
 
+/// Aborts with an error.
+///
+/// This is a thin alias for `fail!` that reads more naturally at call
+/// sites where you mean "abort now" rather than "fail with this error".
+#[macro_export]
+macro_rules! bail {
+    ($($expr:expr),*) => (fail!($($expr),*));
+}
+
+/// Aborts with an error unless a condition holds.
+///
+/// `ensure!(cond, kind, desc)` expands to `if !(cond) { fail!(kind, desc); }`,
+/// so precondition checks become one-liners that still land correctly in
+/// the error trace, with the usual tuple-argument forms supported by
+/// `fail!`.
+#[macro_export]
+macro_rules! ensure {
+    ($cond:expr, $($expr:expr),*) => (
+        if !($cond) {
+            fail!($($expr),*);
+        }
+    );
+}
+
+/// Like `try!`, but treats a recoverable error as a value to fall back on
+/// instead of propagating it.
+///
+/// `try_recover!(expr, fallback)` unwraps the success value of `expr`
+/// like `try!`.  If `expr` is an error whose `severity()` is
+/// `Recoverable`, `fallback` is evaluated instead; a `Fatal` error is
+/// always propagated via `fail!`.
+#[macro_export]
+macro_rules! try_recover {
+    ($expr:expr, $fallback:expr) => (
+        match $expr {
+            Ok(x) => x,
+            Err(err) => {
+                if ::welder::Error::severity(&err) == ::welder::Severity::Recoverable {
+                    $fallback
+                } else {
+                    fail!(err)
+                }
+            }
+        }
+    )
+}
+
 /// Unwraps a value and propagates errors.
 ///
 /// If an expression is wrapped in the `try!` macro this will expand unwrap
@@ -281,6 +391,12 @@ macro_rules! try {
     })
 }
 
+pub use context::{WithContext, ResultExt, IntoMessage};
+
+mod context;
+
+pub mod fs;
+
 /// Helper for formatting errors.
 pub struct ErrorFormatter<W: Writer> {
     writer: W,
@@ -343,8 +459,86 @@ impl<W: Writer> ErrorFormatter<W> {
             None => {}
         }
         try!(writeln!(self.writer, ""));
+
+        if let Some(backtrace) = err.backtrace() {
+            try!(write!(self.writer, "{}", backtrace));
+        }
+
         Ok(())
     }
+
+    /// Serializes the entire cause chain of an error into a machine
+    /// readable JSON array, suitable for logging pipelines.
+    ///
+    /// Each element is an object with `name`, `description`, `detail`,
+    /// `location` (`file`/`line`/`col`, or `null`) and `kind`, ordered
+    /// the same way `format_trace` walks the chain (most recent error
+    /// last).
+    pub fn format_trace_json(&mut self, err: &Error) -> io::IoResult<()> {
+        let mut causes = vec![];
+        let mut cur_err = Some(err);
+        while let Some(x) = cur_err {
+            causes.push(x);
+            cur_err = x.cause();
+        }
+        causes.reverse();
+
+        try!(write!(self.writer, "["));
+        for (idx, cause) in causes.iter().enumerate() {
+            if idx != 0 {
+                try!(write!(self.writer, ","));
+            }
+            try!(self.format_cause_json(*cause));
+        }
+        try!(write!(self.writer, "]"));
+        Ok(())
+    }
+
+    /// Serializes a single error cause into a JSON object.
+    fn format_cause_json(&mut self, err: &Error) -> io::IoResult<()> {
+        try!(write!(self.writer, "{{\"name\":"));
+        try!(write_json_string(&mut self.writer, err.name()));
+        try!(write!(self.writer, ",\"description\":"));
+        try!(write_json_string(&mut self.writer, err.description()));
+
+        try!(write!(self.writer, ",\"detail\":"));
+        match err.detail() {
+            Some(detail) => try!(write_json_string(&mut self.writer, detail.as_slice())),
+            None => try!(write!(self.writer, "null")),
+        }
+
+        try!(write!(self.writer, ",\"location\":"));
+        match err.location() {
+            Some(loc) => {
+                try!(write!(self.writer, "{{\"file\":"));
+                try!(write_json_string(&mut self.writer, loc.file.display().to_string().as_slice()));
+                try!(write!(self.writer, ",\"line\":{},\"col\":{}}}", loc.line, loc.col));
+            }
+            None => try!(write!(self.writer, "null")),
+        }
+
+        try!(write!(self.writer, ",\"kind\":"));
+        try!(write_json_string(&mut self.writer, err.kind().to_string().as_slice()));
+        try!(write!(self.writer, "}}"));
+        Ok(())
+    }
+}
+
+/// Writes a JSON-escaped string literal, including the surrounding quotes.
+fn write_json_string<W: Writer>(writer: &mut W, s: &str) -> io::IoResult<()> {
+    try!(write!(writer, "\""));
+    for c in s.chars() {
+        match c {
+            '"' => try!(write!(writer, "\\\"")),
+            '\\' => try!(write!(writer, "\\\\")),
+            '\n' => try!(write!(writer, "\\n")),
+            '\r' => try!(write!(writer, "\\r")),
+            '\t' => try!(write!(writer, "\\t")),
+            c if (c as u32) < 0x20 => try!(write!(writer, "\\u{:04x}", c as u32)),
+            c => try!(write!(writer, "{}", c)),
+        }
+    }
+    write!(writer, "\"")
 }
 
 /// Helper function to print the error cause stack to stderr.
@@ -353,6 +547,12 @@ pub fn print_error_stack(err: &Error) {
     let _ = fmt.format_trace(err);
 }
 
+/// Helper function to print the error cause stack to stderr as JSON.
+pub fn print_error_stack_json(err: &Error) {
+    let mut fmt = ErrorFormatter::new(std::io::stdio::stderr());
+    let _ = fmt.format_trace_json(err);
+}
+
 
 // default implementations of errors
 impl Error for io::IoError {
@@ -368,4 +568,15 @@ impl Error for io::IoError {
     fn detail(&self) -> Option<String> {
         self.detail.clone()
     }
+
+    fn kind(&self) -> ErrorKind {
+        match self.kind {
+            io::FileNotFound | io::PathDoesntExist => ErrorKind::NotFound,
+            io::PermissionDenied => ErrorKind::PermissionDenied,
+            io::PathAlreadyExists => ErrorKind::AlreadyExists,
+            io::InvalidInput => ErrorKind::InvalidInput,
+            io::TimedOut => ErrorKind::TimedOut,
+            _ => ErrorKind::Other,
+        }
+    }
 }